@@ -1,9 +1,66 @@
+/// Default request-body cap when `FJ_MAX_BODY_BYTES` isn't set (1 MiB).
+const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Runtime configuration, read once at start-up and shared read-only with the
+/// request handlers.
 pub struct Config {
     file_location: String,
     meilisearch: Option<MeilisearchConfig>,
+    max_body_bytes: usize,
 }
 
 pub struct MeilisearchConfig {
     host: String,
     api_key: String,
 }
+
+impl Config {
+    /// Builds a `Config` from the process environment.
+    ///
+    /// `FJ_DATA_DIR` selects the LMDB data directory (defaulting to the current
+    /// working directory). `FJ_MEILI_HOST`/`FJ_MEILI_API_KEY` enable the
+    /// OpenFoodFacts search backend when both are present. `FJ_MAX_BODY_BYTES`
+    /// caps the size of request payloads.
+    pub fn from_env() -> Self {
+        let meilisearch = match (
+            std::env::var("FJ_MEILI_HOST").ok(),
+            std::env::var("FJ_MEILI_API_KEY").ok(),
+        ) {
+            (Some(host), Some(api_key)) => Some(MeilisearchConfig { host, api_key }),
+            _ => None,
+        };
+
+        let max_body_bytes = std::env::var("FJ_MAX_BODY_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_BODY_BYTES);
+
+        Config {
+            file_location: std::env::var("FJ_DATA_DIR").unwrap_or_else(|_| ".".to_string()),
+            meilisearch,
+            max_body_bytes,
+        }
+    }
+
+    pub fn file_location(&self) -> &str {
+        &self.file_location
+    }
+
+    pub fn meilisearch(&self) -> Option<&MeilisearchConfig> {
+        self.meilisearch.as_ref()
+    }
+
+    pub fn max_body_bytes(&self) -> usize {
+        self.max_body_bytes
+    }
+}
+
+impl MeilisearchConfig {
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+}
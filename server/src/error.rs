@@ -1,15 +1,58 @@
 use oxhttp::model::{Response, Status};
+use std::fmt;
 
-pub struct FjError(anyhow::Error);
+/// A handler error that carries the HTTP status to report. Handlers surface
+/// these through `anyhow`; [`From<anyhow::Error>`] downcasts back to recover
+/// the status, defaulting to `500` for ordinary errors.
+#[derive(Debug)]
+pub struct FjError {
+    status: Status,
+    message: String,
+}
+
+impl FjError {
+    pub fn new(status: Status, message: impl Into<String>) -> Self {
+        FjError {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        FjError::new(Status::UNAUTHORIZED, message)
+    }
+
+    pub fn payload_too_large(message: impl Into<String>) -> Self {
+        FjError::new(Status::PAYLOAD_TOO_LARGE, message)
+    }
+
+    pub fn unsupported_media_type(message: impl Into<String>) -> Self {
+        FjError::new(Status::UNSUPPORTED_MEDIA_TYPE, message)
+    }
+}
+
+impl fmt::Display for FjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for FjError {}
 
 impl From<anyhow::Error> for FjError {
     fn from(e: anyhow::Error) -> Self {
-        FjError(e)
+        match e.downcast::<FjError>() {
+            Ok(fj) => fj,
+            Err(e) => FjError {
+                status: Status::INTERNAL_SERVER_ERROR,
+                message: e.to_string(),
+            },
+        }
     }
 }
 
 impl From<FjError> for Response {
     fn from(e: FjError) -> Response {
-        Response::builder(Status::INTERNAL_SERVER_ERROR).with_body(e.0.to_string())
+        Response::builder(e.status).with_body(e.message)
     }
 }
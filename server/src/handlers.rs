@@ -0,0 +1,5 @@
+pub mod end_day;
+pub mod journal;
+pub mod measurements;
+pub mod summary;
+pub mod users;
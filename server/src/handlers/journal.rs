@@ -1,15 +1,15 @@
 use crate::error::FjError;
-use crate::handlers::end_day::get_current_date;
-use crate::state::extract_user_id;
-use heed::types::Str;
-use heed::{Database, Env};
+use crate::state::{extract_user_id, DateRange, FjState, Storage};
 use oxhttp::model::{Request, Response, Status};
 use serde::{Deserialize, Serialize};
-use std::io::Read;
 use std::time::SystemTime;
 
 #[derive(Deserialize)]
 struct JournalEntry {
+    /// Stable entry id. Absent on a first insert (the server mints one),
+    /// present to edit an existing entry so totals adjust by delta.
+    #[serde(default)]
+    id: Option<String>,
     /// Simple input, what did I eat?
     text: String,
     qty: f64,
@@ -21,6 +21,16 @@ struct JournalEntry {
     protein: u64,
 }
 
+/// Running per-day totals, maintained alongside the individual entries so the
+/// summary endpoint can be served without rescanning the journal.
+#[derive(Default, Deserialize, Serialize)]
+pub struct DailyTotals {
+    pub calories: u64,
+    pub carbohydrate: u64,
+    pub fat: u64,
+    pub protein: u64,
+}
+
 #[derive(Deserialize, Serialize)]
 struct JournalEntryDbRecord<'a> {
     text: &'a str,
@@ -72,60 +82,66 @@ struct JournalApiResponse<'a> {
     records: Vec<JournalEntryResponse<'a>>,
 }
 
-pub fn journal(r: &mut Request, db_env: &Env, db: &Database<Str, Str>) -> Response {
+pub fn journal<S: Storage>(r: &mut Request, state: &FjState<S>) -> Response {
     let res = match &**r.method() {
-        "GET" => get_journal(r, db_env, db),
-        "POST" => post_journal(r, db_env, db),
+        "GET" => get_journal(r, state),
+        "POST" => post_journal(r, state),
         _ => Ok(Response::builder(Status::METHOD_NOT_ALLOWED).build()),
     };
 
     res.unwrap_or_else(|err| FjError::from(err).into())
 }
 
-fn get_journal(r: &mut Request, db_env: &Env, db: &Database<Str, Str>) -> anyhow::Result<Response> {
-    let user_id = extract_user_id(&r)?;
-    let prefix_len = "entry..".len() + user_id.len();
+fn get_journal<S: Storage>(r: &mut Request, state: &FjState<S>) -> anyhow::Result<Response> {
+    let storage = &state.storage;
+    let user_id = extract_user_id(r, storage)?;
 
-    let mut records = Vec::new();
-    let rtxn = db_env.read_txn()?;
+    let rtxn = storage.read_txn()?;
+    let entries = storage.iter_journal_entries(&rtxn, &user_id, &DateRange::all())?;
+    drop(rtxn);
 
-    for record in db.prefix_iter(&rtxn, &format!("entry.{user_id}."))? {
-        let (key, value) = record?;
-        let record = serde_json::from_str::<JournalEntryDbRecord>(value)?;
-        records.push(record.as_response_with_id(&key[prefix_len..]));
-    }
+    let records = entries
+        .iter()
+        .map(|(id, value)| {
+            Ok(serde_json::from_str::<JournalEntryDbRecord>(value)?.as_response_with_id(id))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
     let res = serde_json::to_vec(&JournalApiResponse { records })?;
 
-    rtxn.commit()?;
-
     Ok(Response::builder(Status::OK).with_body(res))
 }
 
 /// Posts a food log to your journal.
-fn post_journal(
-    r: &mut Request,
-    db_env: &Env,
-    db: &Database<Str, Str>,
-) -> anyhow::Result<Response> {
-    // Read the request payload
-    let mut buf = Vec::new();
-    let mut body = r.body_mut();
-    body.read_to_end(&mut buf)?;
+fn post_journal<S: Storage>(r: &mut Request, state: &FjState<S>) -> anyhow::Result<Response> {
+    // Read the request payload (size-capped, JSON-only).
+    let buf = state.read_json_body(r)?;
     let payload = serde_json::from_slice::<JournalEntry>(&buf)?;
 
-    // Get the user's ID from headers
-    let user_id = extract_user_id(&r)?;
+    // Resolve the user's ID from their session.
+    let storage = &state.storage;
+    let user_id = extract_user_id(r, storage)?;
 
     // Start a database transaction
-    let mut wtxn = db_env.write_txn()?;
+    let mut wtxn = storage.write_txn()?;
 
-    let user_key = format!("user.{user_id}");
-    let (date, _) = get_current_date(&user_key, db, &mut wtxn)?;
+    let current_date = storage.get_current_date(&wtxn, &user_id)?;
     let timestamp = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)?
         .as_millis();
 
+    // A caller-supplied id means this is an edit; otherwise mint a new stable
+    // id as the `{date}.{timestamp}` suffix that `GET /journal` hands back, so
+    // the create and edit paths key identically.
+    let id = payload
+        .id
+        .clone()
+        .unwrap_or_else(|| format!("{current_date}.{timestamp}"));
+
+    // The entry's own date is embedded in its id, so an edit after an end-day
+    // rollover still adjusts the original day's totals rather than today's.
+    let date = id.split('.').next().unwrap_or(&current_date).to_string();
+
     let record = JournalEntryDbRecord {
         text: &payload.text,
         timestamp,
@@ -139,13 +155,32 @@ fn post_journal(
 
     let record = serde_json::to_string(&record)?;
 
-    let entry_key = format!("entry.{user_id}.{date}.{timestamp}");
-    let recall_key = format!("food.{}", &payload.text[..16]);
-
-    // Insert the record twice with two different keys, one for recall and one for the journal.
-    db.put(&mut wtxn, &entry_key, &record)?;
-    db.put(&mut wtxn, &recall_key, &record)?;
-    wtxn.commit()?;
-
-    Ok(Response::builder(Status::NO_CONTENT).build())
+    // Fold the change into the day's totals as a delta so an edit replaces the
+    // previous values rather than double-counting them.
+    let previous = storage.get_journal_entry(&wtxn, &user_id, &id)?;
+    let mut totals = match storage.get_total(&wtxn, &user_id, &date)? {
+        Some(raw) => serde_json::from_str::<DailyTotals>(&raw)?,
+        None => DailyTotals::default(),
+    };
+    if let Some(previous) = previous {
+        let old = serde_json::from_str::<JournalEntryDbRecord>(&previous)?;
+        totals.calories = totals.calories.saturating_sub(old.calories);
+        totals.carbohydrate = totals.carbohydrate.saturating_sub(old.carbohydrate);
+        totals.fat = totals.fat.saturating_sub(old.fat);
+        totals.protein = totals.protein.saturating_sub(old.protein);
+    }
+    totals.calories += payload.calories;
+    totals.carbohydrate += payload.carbohydrate;
+    totals.fat += payload.fat;
+    totals.protein += payload.protein;
+
+    // Write the entry, its recall copy, and the refreshed totals in one txn.
+    storage.put_journal_entry(&mut wtxn, &user_id, &id, &record)?;
+    storage.put_food_recall(&mut wtxn, &payload.text, &record)?;
+    storage.put_total(&mut wtxn, &user_id, &date, &serde_json::to_string(&totals)?)?;
+    storage.commit(wtxn)?;
+
+    Ok(Response::builder(Status::OK)
+        .with_header(oxhttp::model::HeaderName::CONTENT_TYPE, "application/json")?
+        .with_body(format!(r#"{{"id": "{id}"}}"#)))
 }
@@ -0,0 +1,115 @@
+use crate::error::FjError;
+use crate::state::{extract_user_id, today_utc, DateRange, FjState, Storage};
+use jiff::civil::Date;
+use jiff::ToSpan;
+use oxhttp::model::{HeaderName, Request, Response, Status};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Default charting window, in days, for `GET /v1/<user>/measurements`.
+const DEFAULT_WINDOW_DAYS: i64 = 90;
+
+#[derive(Deserialize)]
+struct MeasurementRequest {
+    /// Measurement date; defaults to today (UTC) when omitted.
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(flatten)]
+    body: MeasurementBody,
+}
+
+/// The measured values, stored verbatim under the `measurement.` key. `metrics`
+/// holds arbitrary user-defined series so people can track whatever their goal
+/// needs beyond the well-known fields.
+#[derive(Deserialize, Serialize)]
+struct MeasurementBody {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    weight: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_fat: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    waist: Option<f64>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    metrics: HashMap<String, f64>,
+}
+
+#[derive(Serialize)]
+struct Measurement {
+    date: String,
+    #[serde(flatten)]
+    body: MeasurementBody,
+}
+
+#[derive(Serialize)]
+struct MeasurementsResponse {
+    measurements: Vec<Measurement>,
+}
+
+pub fn http_measurements<S: Storage>(r: &mut Request, state: &FjState<S>) -> Response {
+    let res = match &**r.method() {
+        "GET" => get_measurements(r, state),
+        "POST" => post_measurement(r, state),
+        _ => Ok(Response::builder(Status::METHOD_NOT_ALLOWED).build()),
+    };
+
+    res.unwrap_or_else(|err| FjError::from(err).into())
+}
+
+fn get_measurements<S: Storage>(r: &mut Request, state: &FjState<S>) -> anyhow::Result<Response> {
+    let storage = &state.storage;
+    let user_id = extract_user_id(r, storage)?;
+
+    let days = r
+        .url()
+        .query_pairs()
+        .find(|(k, _)| k == "days")
+        .and_then(|(_, v)| v.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_WINDOW_DAYS);
+
+    let start = Date::from_str(&today_utc()?)?
+        .checked_sub(days.days())?
+        .to_string();
+    let range = DateRange {
+        start: Some(start),
+        end: None,
+    };
+
+    let rtxn = storage.read_txn()?;
+    let rows = storage.iter_measurements(&rtxn, &user_id, &range)?;
+    drop(rtxn);
+
+    let measurements = rows
+        .into_iter()
+        .map(|(date, value)| {
+            Ok(Measurement {
+                date,
+                body: serde_json::from_str(&value)?,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(Response::builder(Status::OK)
+        .with_header(HeaderName::CONTENT_TYPE, "application/json")?
+        .with_body(serde_json::to_vec(&MeasurementsResponse { measurements })?))
+}
+
+fn post_measurement<S: Storage>(r: &mut Request, state: &FjState<S>) -> anyhow::Result<Response> {
+    let buf = state.read_json_body(r)?;
+    let request = serde_json::from_slice::<MeasurementRequest>(&buf)?;
+
+    let storage = &state.storage;
+    let user_id = extract_user_id(r, storage)?;
+
+    let date = match request.date {
+        Some(date) => date,
+        None => today_utc()?,
+    };
+    let record = serde_json::to_string(&request.body)?;
+
+    let mut wtxn = storage.write_txn()?;
+    storage.put_measurement(&mut wtxn, &user_id, &date, &record)?;
+    storage.commit(wtxn)?;
+
+    Ok(Response::builder(Status::NO_CONTENT).build())
+}
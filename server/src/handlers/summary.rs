@@ -0,0 +1,72 @@
+use crate::error::FjError;
+use crate::handlers::journal::DailyTotals;
+use crate::state::{extract_user_id, FjState, Storage};
+use oxhttp::model::{HeaderName, Request, Response, Status};
+use serde::{Deserialize, Serialize};
+
+/// The macro targets copied off the user record, matching `DailyTotals` so the
+/// front end can diff consumed against target field-for-field.
+#[derive(Default, Deserialize, Serialize)]
+struct Targets {
+    #[serde(rename = "target_calories")]
+    calories: u64,
+    #[serde(rename = "target_carbohydrate")]
+    carbohydrate: u64,
+    #[serde(rename = "target_fat")]
+    fat: u64,
+    #[serde(rename = "target_protein")]
+    protein: u64,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    date: String,
+    consumed: DailyTotals,
+    target: Targets,
+    remaining: DailyTotals,
+}
+
+pub fn http_summary<S: Storage>(r: &mut Request, state: &FjState<S>) -> Response {
+    summary(r, state).unwrap_or_else(|err| FjError::from(err).into())
+}
+
+fn summary<S: Storage>(r: &mut Request, state: &FjState<S>) -> anyhow::Result<Response> {
+    let storage = &state.storage;
+    let user_id = extract_user_id(r, storage)?;
+
+    let rtxn = storage.read_txn()?;
+
+    // Default the date to the user's current logical day.
+    let date = match r.url().query_pairs().find(|(k, _)| k == "date") {
+        Some((_, v)) => v.into_owned(),
+        None => storage.get_current_date(&rtxn, &user_id)?,
+    };
+
+    let consumed = match storage.get_total(&rtxn, &user_id, &date)? {
+        Some(raw) => serde_json::from_str::<DailyTotals>(&raw)?,
+        None => DailyTotals::default(),
+    };
+    let target = match storage.get_user(&rtxn, &user_id)? {
+        Some(raw) => serde_json::from_str::<Targets>(&raw)?,
+        None => Targets::default(),
+    };
+    drop(rtxn);
+
+    let remaining = DailyTotals {
+        calories: target.calories.saturating_sub(consumed.calories),
+        carbohydrate: target.carbohydrate.saturating_sub(consumed.carbohydrate),
+        fat: target.fat.saturating_sub(consumed.fat),
+        protein: target.protein.saturating_sub(consumed.protein),
+    };
+
+    let summary = Summary {
+        date,
+        consumed,
+        target,
+        remaining,
+    };
+
+    Ok(Response::builder(Status::OK)
+        .with_header(HeaderName::CONTENT_TYPE, "application/json")?
+        .with_body(serde_json::to_vec(&summary)?))
+}
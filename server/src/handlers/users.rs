@@ -1,22 +1,68 @@
 use crate::error::FjError;
-use heed::types::Str;
-use heed::{Database, Env};
+use crate::state::{
+    new_session_token, now_secs, today_utc, FjState, SessionRecord, Storage, SESSION_COOKIE,
+    SESSION_TTL_SECS,
+};
+use anyhow::anyhow;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{password_hash::rand_core::OsRng, Argon2};
 use oxhttp::model::{HeaderName, Request, Response, Status};
 use serde::{Deserialize, Serialize};
-use std::io::Read;
-use std::str::FromStr;
 
 #[derive(Clone, Deserialize)]
 pub struct UserRequest {
     image: String,
     user_name: String,
     display_name: String,
+    /// Plaintext password, hashed with Argon2 before it ever touches the DB.
+    password: String,
     age: u64,
     gender: Gender,
     goal: FitnessGoal,
     factor: ActivityFactor,
     height: u64,
     weight: u64,
+    /// Desired macronutrient split; defaults to 50/30/20 (carb/protein/fat).
+    #[serde(default)]
+    macro_split: MacroSplit,
+}
+
+/// A carbohydrate/protein/fat calorie split, in whole percent. Must sum to 100.
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct MacroSplit {
+    carbohydrate: u64,
+    protein: u64,
+    fat: u64,
+}
+
+impl Default for MacroSplit {
+    fn default() -> Self {
+        MacroSplit {
+            carbohydrate: 50,
+            protein: 30,
+            fat: 20,
+        }
+    }
+}
+
+impl MacroSplit {
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.carbohydrate + self.protein + self.fat != 100 {
+            return Err(FjError::new(
+                Status::BAD_REQUEST,
+                "macro split percentages must sum to 100",
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Credentials posted to `POST /v1/login`.
+#[derive(Deserialize)]
+pub struct LoginRequest {
+    user_name: String,
+    password: String,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -25,6 +71,15 @@ pub struct UserDbRecord<'a> {
     image: &'a str,
     #[serde(borrow)]
     display_name: &'a str,
+    /// Argon2 PHC string; never leaves the server.
+    #[serde(borrow)]
+    password_hash: &'a str,
+    /// The user's current logical day, advanced by the end-day clock and read
+    /// back by `get_current_date`; seeded to today (UTC) at registration.
+    #[serde(borrow)]
+    current_date: &'a str,
+    #[serde(default)]
+    macro_split: MacroSplit,
     target_calories: u64,
     target_fat: u64,
     target_protein: u64,
@@ -102,23 +157,15 @@ impl From<ActivityFactor> for f64 {
     }
 }
 
-pub fn http_post_register(r: &mut Request, db_env: &Env, db: &Database<Str, Str>) -> Response {
+pub fn http_post_register<S: Storage>(r: &mut Request, state: &FjState<S>) -> Response {
     // This function is a little wrapper that converts anyhow errors into FjErrors to allow ? use.
-    post_register(r, db_env, db).unwrap_or_else(|err| FjError::from(err).into())
+    post_register(r, state).unwrap_or_else(|err| FjError::from(err).into())
 }
 
-fn post_register(
-    r: &mut Request,
-    db_env: &Env,
-    db: &Database<Str, Str>,
-) -> anyhow::Result<Response> {
-    // TODO: It would be nice if we checked the Content-Type header for some kind of JSON.
-    let mut name = Vec::new();
-    let mut body = r.body_mut();
-    body.read_to_end(&mut name)?;
-
+fn post_register<S: Storage>(r: &mut Request, state: &FjState<S>) -> anyhow::Result<Response> {
+    let name = state.read_json_body(r)?;
     let request = serde_json::from_slice::<UserRequest>(&name)?;
-    let data = register(request.clone(), db_env, db)?;
+    let data = register(request.clone(), state)?;
 
     Ok(Response::builder(Status::CREATED)
         .with_header(HeaderName::CONTENT_TYPE, "application/json")?
@@ -126,11 +173,7 @@ fn post_register(
         .into())
 }
 
-fn register(
-    request: UserRequest,
-    db_env: &Env,
-    db: &Database<Str, Str>,
-) -> anyhow::Result<Vec<u8>> {
+fn register<S: Storage>(request: UserRequest, state: &FjState<S>) -> anyhow::Result<Vec<u8>> {
     let target_calories = target_macros2(
         request.height,
         request.weight,
@@ -139,11 +182,22 @@ fn register(
         request.gender,
         request.factor,
     );
-    let macros = target_macros(target_calories);
+    request.macro_split.validate()?;
+    let macros = target_macros(
+        target_calories,
+        request.macro_split,
+        request.weight,
+        request.goal,
+    );
+    let password_hash = hash_password(&request.password)?;
+    let current_date = today_utc()?;
 
     let record = UserDbRecord {
         image: &request.image,
         display_name: &request.display_name,
+        password_hash: &password_hash,
+        current_date: &current_date,
+        macro_split: request.macro_split,
         target_calories: target_calories,
         target_fat: macros.target_fat,
         target_protein: macros.target_protein,
@@ -151,13 +205,14 @@ fn register(
     };
     let record_json = serde_json::to_string(&record).unwrap();
 
-    let mut wtxn = db_env.write_txn()?;
-    db.put(
-        &mut wtxn,
-        &format!("user.{}", request.user_name),
-        &record_json,
-    )?;
-    wtxn.commit()?;
+    let storage = &state.storage;
+    let mut wtxn = storage.write_txn()?;
+    storage.put_user(&mut wtxn, &request.user_name, &record_json)?;
+    // Seed an initial weight measurement so the body-measurement chart has a
+    // starting point from day one.
+    let seed = format!(r#"{{"weight":{}}}"#, request.weight);
+    storage.put_measurement(&mut wtxn, &request.user_name, &current_date, &seed)?;
+    storage.commit(wtxn)?;
 
     Ok(serde_json::to_vec(&UserResponse {
         image: &request.image,
@@ -170,12 +225,12 @@ fn register(
     })?)
 }
 
-pub fn http_get_users(db_env: &Env, db: &Database<Str, Str>) -> Response {
-    get_users_inner(db_env, db).unwrap_or_else(|err| FjError::from(err).into())
+pub fn http_get_users<S: Storage>(state: &FjState<S>) -> Response {
+    get_users_inner(state).unwrap_or_else(|err| FjError::from(err).into())
 }
 
-fn get_users_inner(db_env: &Env, db: &Database<Str, Str>) -> anyhow::Result<Response> {
-    let data = get_users(db_env, db)?;
+fn get_users_inner<S: Storage>(state: &FjState<S>) -> anyhow::Result<Response> {
+    let data = get_users(state)?;
 
     Ok(Response::builder(Status::OK)
         .with_header(HeaderName::CONTENT_TYPE, "application/json")?
@@ -183,20 +238,20 @@ fn get_users_inner(db_env: &Env, db: &Database<Str, Str>) -> anyhow::Result<Resp
         .into())
 }
 
-fn get_users(db_env: &Env, db: &Database<Str, Str>) -> anyhow::Result<Vec<u8>> {
-    let mut users = Vec::new();
-    let rtxn = db_env.read_txn()?;
-
-    for record in db.prefix_iter(&rtxn, "user.")? {
-        let (key, value) = record?;
-        let record = serde_json::from_str::<UserDbRecord>(value)?;
-        users.push(record.into_response_with_username(&key[5..]));
-    }
+fn get_users<S: Storage>(state: &FjState<S>) -> anyhow::Result<Vec<u8>> {
+    let storage = &state.storage;
+    let rtxn = storage.read_txn()?;
+    let records = storage.iter_users(&rtxn)?;
+    drop(rtxn);
 
-    let res = serde_json::to_vec(&UsersApiResponse { users })?;
+    let users = records
+        .iter()
+        .map(|(user_name, value)| {
+            Ok(serde_json::from_str::<UserDbRecord>(value)?.into_response_with_username(user_name))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
-    rtxn.commit()?;
-    Ok(res)
+    Ok(serde_json::to_vec(&UsersApiResponse { users })?)
 }
 
 /// Implements the Mifflin-St. Jeor algorithm for nutrition targets.
@@ -224,11 +279,106 @@ fn target_macros2(
     calories
 }
 
-/// Sets target macros based on the simple 50/30/20 rule.
-fn target_macros(target_calories: u64) -> TargetMacros {
+/// Converts a calorie goal into macronutrient targets, in grams, using the
+/// energy densities of 4 kcal/g for carbohydrate and protein and 9 kcal/g for
+/// fat.
+///
+/// When the goal is to lose weight we enforce a protein floor of 1.6 g/kg of
+/// body weight, taking the larger of the percentage- and weight-based protein
+/// targets and rebalancing the remaining calories across carbohydrate and fat
+/// in their original proportion.
+fn target_macros(
+    target_calories: u64,
+    split: MacroSplit,
+    weight_kg: u64,
+    goal: FitnessGoal,
+) -> TargetMacros {
+    let calories = target_calories as f64;
+
+    let mut protein_g = (split.protein as f64 / 100.0 * calories) / 4.0;
+    let mut carb_g = (split.carbohydrate as f64 / 100.0 * calories) / 4.0;
+    let mut fat_g = (split.fat as f64 / 100.0 * calories) / 9.0;
+
+    if let FitnessGoal::LoseWeight = goal {
+        let protein_floor = 1.6 * weight_kg as f64;
+        if protein_floor > protein_g {
+            protein_g = protein_floor;
+
+            // Split whatever calories remain between carbohydrate and fat in the
+            // same ratio the user requested.
+            let remaining = (calories - protein_g * 4.0).max(0.0);
+            let non_protein = (split.carbohydrate + split.fat) as f64;
+            if non_protein > 0.0 {
+                carb_g = (remaining * split.carbohydrate as f64 / non_protein) / 4.0;
+                fat_g = (remaining * split.fat as f64 / non_protein) / 9.0;
+            } else {
+                carb_g = 0.0;
+                fat_g = 0.0;
+            }
+        }
+    }
+
     TargetMacros {
-        target_fat: target_calories / 8,
-        target_protein: target_calories / 12,
-        target_carbohydrate: target_calories / 45,
+        target_fat: fat_g.round() as u64,
+        target_protein: protein_g.round() as u64,
+        target_carbohydrate: carb_g.round() as u64,
+    }
+}
+
+pub fn http_post_login<S: Storage>(r: &mut Request, state: &FjState<S>) -> Response {
+    login(r, state).unwrap_or_else(|err| FjError::from(err).into())
+}
+
+/// Verifies a password and, on success, mints a session token returned both in
+/// the body and as an `HttpOnly` cookie.
+fn login<S: Storage>(r: &mut Request, state: &FjState<S>) -> anyhow::Result<Response> {
+    let buf = state.read_json_body(r)?;
+    let request = serde_json::from_slice::<LoginRequest>(&buf)?;
+
+    let storage = &state.storage;
+    let rtxn = storage.read_txn()?;
+    let record = storage.get_user(&rtxn, &request.user_name)?;
+    drop(rtxn);
+
+    let record = record.ok_or_else(|| FjError::unauthorized("invalid credentials"))?;
+    let user = serde_json::from_str::<UserDbRecord>(&record)?;
+    if !verify_password(&request.password, user.password_hash) {
+        return Err(FjError::unauthorized("invalid credentials").into());
     }
+
+    let token = new_session_token();
+    let session = SessionRecord {
+        user_id: request.user_name,
+        created: now_secs(),
+    };
+    let mut wtxn = storage.write_txn()?;
+    storage.put_session(&mut wtxn, &token, &serde_json::to_string(&session)?)?;
+    storage.commit(wtxn)?;
+
+    let cookie = format!("{SESSION_COOKIE}={token}; HttpOnly; Path=/; Max-Age={SESSION_TTL_SECS}");
+    Ok(Response::builder(Status::OK)
+        .with_header(HeaderName::SET_COOKIE, cookie)?
+        .with_header(HeaderName::CONTENT_TYPE, "application/json")?
+        .with_body(format!(r#"{{"token": "{token}"}}"#))
+        .into())
+}
+
+/// Hashes a password with Argon2 using a fresh salt, returning a PHC string.
+fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("failed to hash password: {e}"))?
+        .to_string())
+}
+
+/// Verifies a password against a stored PHC string. Returns `false` on any
+/// parse or mismatch error so callers can treat it as a plain boolean.
+fn verify_password(password: &str, phc: &str) -> bool {
+    let Ok(parsed) = PasswordHash::new(phc) else {
+        return false;
+    };
+    Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_ok()
 }
@@ -9,6 +9,7 @@ mod config;
 mod error;
 mod handlers;
 mod model;
+mod search;
 mod state;
 
 /// Notes:
@@ -39,24 +40,38 @@ mod state;
 fn main() {
     println!("Hello, world!");
 
+    // Read configuration (data dir + optional Meilisearch backend) from env.
+    let config = config::Config::from_env();
+
     // Open db
-    let dir = std::env::current_dir().unwrap();
-    let env = unsafe { EnvOpenOptions::new().open(dir).unwrap() };
+    let env = unsafe { EnvOpenOptions::new().open(config.file_location()).unwrap() };
 
     // TODO: Maybe open dbs for each user
 
     // Creates a default database, but...
     let mut wtxn = env.write_txn().unwrap();
-    let db: Database<Str, Str> = env.clone().create_database(&mut wtxn, None).unwrap();
+    let db: Database<Str, Str> = env.create_database(&mut wtxn, None).unwrap();
     wtxn.commit().unwrap();
 
-    // TODO: We should definitely check content-length of request payloads and discard large ones.
+    // The request handlers persist through the `Storage` trait; the food search
+    // and OpenFoodFacts loader still use the underlying heed env and db.
+    let state = state::FjState::new(state::LmdbStorage::new(env, db), config.max_body_bytes());
+
     let mut server = Server::new(move |request| match request.url().path() {
         "/" => Response::builder(Status::OK).with_body("home"),
-        "/v1/users" => handlers::users::http_get_users(&env, &db),
-        "/v1/register" => handlers::users::http_post_register(request, &env, &db),
-        "/v1/end-day" => handlers::end_day::http_end_day(request, &env, &db),
-        "/journal" => handlers::journal::journal(request, &env, &db),
+        "/v1/users" => handlers::users::http_get_users(&state),
+        "/v1/register" => handlers::users::http_post_register(request, &state),
+        "/v1/login" => handlers::users::http_post_login(request, &state),
+        "/v1/end-day" => handlers::end_day::http_end_day(request, &state),
+        "/v1/food" => search::http_food(request, &state.storage, &config),
+        "/v1/load-off" => search::http_load_off(request, &state.storage, &config),
+        "/journal" => handlers::journal::journal(request, &state),
+        path if path.starts_with("/v1/") && path.ends_with("/measurements") => {
+            handlers::measurements::http_measurements(request, &state)
+        }
+        path if path.starts_with("/v1/") && path.ends_with("/summary") => {
+            handlers::summary::http_summary(request, &state)
+        }
         _ => Response::builder(Status::NOT_FOUND).build(),
     });
 
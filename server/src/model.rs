@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+/// A compact food record as stored in the Meilisearch index. One document per
+/// OpenFoodFacts product, reduced to the handful of fields the journal needs.
+///
+/// All macronutrient values are per 100 g of product, matching the OFF
+/// `*_100g` nutriment fields they are mapped from.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FoodDocument {
+    /// Product barcode; doubles as the Meilisearch primary key.
+    pub barcode: String,
+    pub name: String,
+    pub brand: String,
+    pub serving_size: String,
+    pub kcal: f64,
+    pub fat: f64,
+    pub protein: f64,
+    pub carbohydrate: f64,
+}
+
+impl FoodDocument {
+    /// A result shaped so the front end can drop it straight into a new
+    /// journal entry. Quantities default to 100 g, the basis the macros are
+    /// reported against.
+    pub fn as_search_result(&self) -> FoodSearchResult<'_> {
+        FoodSearchResult {
+            barcode: &self.barcode,
+            text: &self.name,
+            brand: &self.brand,
+            qty: 100.0,
+            qty_units: "g",
+            calories: self.kcal.round() as u64,
+            carbohydrate: self.carbohydrate.round() as u64,
+            fat: self.fat.round() as u64,
+            protein: self.protein.round() as u64,
+        }
+    }
+}
+
+/// A single normalized search hit returned from `GET /v1/food`.
+#[derive(Debug, Serialize)]
+pub struct FoodSearchResult<'a> {
+    pub barcode: &'a str,
+    pub text: &'a str,
+    pub brand: &'a str,
+    pub qty: f64,
+    pub qty_units: &'a str,
+    pub calories: u64,
+    pub carbohydrate: u64,
+    pub fat: u64,
+    pub protein: u64,
+}
+
+/// The wire wrapper for `GET /v1/food` so we can extend the envelope later
+/// without breaking clients, mirroring `UsersApiResponse`.
+#[derive(Debug, Serialize)]
+pub struct FoodSearchResponse<'a> {
+    pub results: Vec<FoodSearchResult<'a>>,
+}
@@ -0,0 +1,425 @@
+use crate::config::{Config, MeilisearchConfig};
+use crate::error::FjError;
+use crate::model::{FoodDocument, FoodSearchResponse, FoodSearchResult};
+use crate::state::LmdbStorage;
+use anyhow::{anyhow, Context};
+use flate2::read::GzDecoder;
+use heed::types::Str;
+use heed::{Database, Env};
+use oxhttp::model::{HeaderName, Request, Response, Status};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+
+/// Name of the Meilisearch index holding OpenFoodFacts products.
+const INDEX: &str = "foods";
+
+/// LMDB key under which the running load-off job publishes its progress.
+const STATUS_KEY: &str = "load-off.status";
+
+/// Public OpenFoodFacts products export (newline-delimited JSON, gzipped).
+const OFF_EXPORT_URL: &str =
+    "https://static.openfoodfacts.org/data/openfoodfacts-products.jsonl.gz";
+
+/// Number of documents pushed to Meilisearch per indexing request.
+const BATCH_SIZE: usize = 1000;
+
+/// Progress of an OpenFoodFacts load, persisted in LMDB so `GET /v1/load-off`
+/// can report it across requests (and after the job thread has exited).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LoadOffStatus {
+    phase: LoadOffPhase,
+    /// Documents indexed so far. The export is streamed, so no meaningful total
+    /// is known up front; `indexed` climbs monotonically until `phase` is
+    /// `Complete`.
+    indexed: u64,
+    /// Populated when `phase` is `Error`.
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum LoadOffPhase {
+    Idle,
+    Downloading,
+    Indexing,
+    Complete,
+    Error,
+}
+
+/// A thin blocking Meilisearch client built from [`MeilisearchConfig`].
+pub struct MeilisearchClient {
+    host: String,
+    api_key: String,
+    agent: ureq::Agent,
+}
+
+impl MeilisearchClient {
+    pub fn from_config(config: &MeilisearchConfig) -> Self {
+        MeilisearchClient {
+            host: config.host().trim_end_matches('/').to_string(),
+            api_key: config.api_key().to_string(),
+            agent: ureq::agent(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.host, path)
+    }
+
+    /// Creates the index if needed and declares the searchable/filterable
+    /// attributes the journal relies on.
+    fn configure_index(&self) -> anyhow::Result<()> {
+        let settings = serde_json::json!({
+            "searchableAttributes": ["name", "brand"],
+            "filterableAttributes": ["barcode"],
+        });
+
+        self.agent
+            .patch(&self.url(&format!("/indexes/{INDEX}/settings")))
+            .set("Authorization", &format!("Bearer {}", self.api_key))
+            .send_json(settings)
+            .context("failed to configure meilisearch index settings")?;
+
+        Ok(())
+    }
+
+    /// Upserts a batch of documents, keyed on `barcode`.
+    fn index_documents(&self, docs: &[FoodDocument]) -> anyhow::Result<()> {
+        self.agent
+            .post(&self.url(&format!("/indexes/{INDEX}/documents?primaryKey=barcode")))
+            .set("Authorization", &format!("Bearer {}", self.api_key))
+            .send_json(serde_json::to_value(docs)?)
+            .context("failed to index documents into meilisearch")?;
+
+        Ok(())
+    }
+
+    /// Runs a query against the index, optionally constrained by a filter
+    /// expression (used for exact barcode lookups).
+    fn search(&self, query: &str, filter: Option<&str>) -> anyhow::Result<Vec<FoodDocument>> {
+        #[derive(Deserialize)]
+        struct SearchResponse {
+            hits: Vec<FoodDocument>,
+        }
+
+        let mut body = serde_json::json!({ "q": query, "limit": 25 });
+        if let Some(filter) = filter {
+            body["filter"] = serde_json::Value::String(filter.to_string());
+        }
+
+        let response: SearchResponse = self
+            .agent
+            .post(&self.url(&format!("/indexes/{INDEX}/search")))
+            .set("Authorization", &format!("Bearer {}", self.api_key))
+            .send_json(body)
+            .context("meilisearch search request failed")?
+            .into_json()?;
+
+        Ok(response.hits)
+    }
+}
+
+fn read_status(db_env: &Env, db: &Database<Str, Str>) -> anyhow::Result<LoadOffStatus> {
+    let rtxn = db_env.read_txn()?;
+    let status = match db.get(&rtxn, STATUS_KEY)? {
+        Some(raw) => serde_json::from_str(raw)?,
+        None => LoadOffStatus {
+            phase: LoadOffPhase::Idle,
+            indexed: 0,
+            error: None,
+        },
+    };
+    rtxn.commit()?;
+    Ok(status)
+}
+
+fn write_status(
+    db_env: &Env,
+    db: &Database<Str, Str>,
+    status: &LoadOffStatus,
+) -> anyhow::Result<()> {
+    let mut wtxn = db_env.write_txn()?;
+    db.put(&mut wtxn, STATUS_KEY, &serde_json::to_string(status)?)?;
+    wtxn.commit()?;
+    Ok(())
+}
+
+/// Maps a raw OpenFoodFacts product line to our compact document, skipping
+/// products missing a barcode or name (there are many in the dump).
+fn map_product(line: &str) -> Option<FoodDocument> {
+    #[derive(Deserialize, Default)]
+    struct Nutriments {
+        #[serde(rename = "energy-kcal_100g", default)]
+        kcal: f64,
+        #[serde(rename = "fat_100g", default)]
+        fat: f64,
+        #[serde(rename = "proteins_100g", default)]
+        protein: f64,
+        #[serde(rename = "carbohydrates_100g", default)]
+        carbohydrate: f64,
+    }
+
+    #[derive(Deserialize)]
+    struct Product {
+        #[serde(default)]
+        code: String,
+        #[serde(default)]
+        product_name: String,
+        #[serde(default)]
+        brands: String,
+        #[serde(default)]
+        serving_size: String,
+        #[serde(default)]
+        nutriments: Nutriments,
+    }
+
+    let product = serde_json::from_str::<Product>(line).ok()?;
+    if product.code.is_empty() || product.product_name.is_empty() {
+        return None;
+    }
+
+    Some(FoodDocument {
+        barcode: product.code,
+        name: product.product_name,
+        brand: product.brands,
+        serving_size: product.serving_size,
+        kcal: product.nutriments.kcal,
+        fat: product.nutriments.fat,
+        protein: product.nutriments.protein,
+        carbohydrate: product.nutriments.carbohydrate,
+    })
+}
+
+/// Streams the OpenFoodFacts export and indexes it in batches, updating the
+/// persisted status as it goes. Runs to completion on the calling thread.
+fn run_load_off(
+    client: &MeilisearchClient,
+    db_env: &Env,
+    db: &Database<Str, Str>,
+) -> anyhow::Result<()> {
+    write_status(
+        db_env,
+        db,
+        &LoadOffStatus {
+            phase: LoadOffPhase::Downloading,
+            indexed: 0,
+            error: None,
+        },
+    )?;
+
+    client.configure_index()?;
+
+    let response = ureq::get(OFF_EXPORT_URL)
+        .call()
+        .context("failed to download OpenFoodFacts export")?;
+    let reader = BufReader::new(GzDecoder::new(response.into_reader()));
+
+    let mut batch: Vec<FoodDocument> = Vec::with_capacity(BATCH_SIZE);
+    let mut indexed: u64 = 0;
+
+    for line in reader.lines() {
+        let line = line?;
+        let Some(doc) = map_product(&line) else {
+            continue;
+        };
+        batch.push(doc);
+
+        if batch.len() >= BATCH_SIZE {
+            client.index_documents(&batch)?;
+            indexed += batch.len() as u64;
+            batch.clear();
+            write_status(
+                db_env,
+                db,
+                &LoadOffStatus {
+                    phase: LoadOffPhase::Indexing,
+                    indexed,
+                    error: None,
+                },
+            )?;
+        }
+    }
+
+    if !batch.is_empty() {
+        client.index_documents(&batch)?;
+        indexed += batch.len() as u64;
+    }
+
+    write_status(
+        db_env,
+        db,
+        &LoadOffStatus {
+            phase: LoadOffPhase::Complete,
+            indexed,
+            error: None,
+        },
+    )?;
+
+    Ok(())
+}
+
+pub fn http_load_off(r: &Request, storage: &LmdbStorage, config: &Config) -> Response {
+    let (db_env, db) = (storage.env(), storage.db());
+    let res = match &**r.method() {
+        "GET" => get_load_off(db_env, &db),
+        "POST" => post_load_off(db_env, &db, config),
+        _ => Ok(Response::builder(Status::METHOD_NOT_ALLOWED).build()),
+    };
+
+    res.unwrap_or_else(|err| FjError::from(err).into())
+}
+
+fn get_load_off(db_env: &Env, db: &Database<Str, Str>) -> anyhow::Result<Response> {
+    let status = read_status(db_env, db)?;
+    Ok(Response::builder(Status::OK)
+        .with_header(HeaderName::CONTENT_TYPE, "application/json")?
+        .with_body(serde_json::to_vec(&status)?))
+}
+
+fn post_load_off(
+    db_env: &Env,
+    db: &Database<Str, Str>,
+    config: &Config,
+) -> anyhow::Result<Response> {
+    let Some(meili) = config.meilisearch() else {
+        return Ok(Response::builder(Status::BAD_REQUEST)
+            .with_body("meilisearch is not configured"));
+    };
+
+    // Index on a background thread so the request returns promptly; progress is
+    // observable through `GET /v1/load-off`.
+    let client = MeilisearchClient::from_config(meili);
+    let db_env = db_env.clone();
+    let db = *db;
+    std::thread::spawn(move || {
+        if let Err(e) = run_load_off(&client, &db_env, &db) {
+            // Keep the count of documents that made it into the index.
+            let indexed = read_status(&db_env, &db).map(|s| s.indexed).unwrap_or(0);
+            let _ = write_status(
+                &db_env,
+                &db,
+                &LoadOffStatus {
+                    phase: LoadOffPhase::Error,
+                    indexed,
+                    error: Some(e.to_string()),
+                },
+            );
+        }
+    });
+
+    Ok(Response::builder(Status::ACCEPTED).build())
+}
+
+pub fn http_food(r: &Request, storage: &LmdbStorage, config: &Config) -> Response {
+    food(r, storage.env(), &storage.db(), config)
+        .unwrap_or_else(|err| FjError::from(err).into())
+}
+
+fn food(
+    r: &Request,
+    db_env: &Env,
+    db: &Database<Str, Str>,
+    config: &Config,
+) -> anyhow::Result<Response> {
+    let term = r
+        .url()
+        .query_pairs()
+        .find(|(k, _)| k == "term")
+        .map(|(_, v)| v.into_owned())
+        .ok_or_else(|| anyhow!("missing `term` query parameter"))?;
+
+    match config.meilisearch() {
+        Some(meili) => {
+            let client = MeilisearchClient::from_config(meili);
+            let docs = if is_barcode(&term) {
+                client.search("", Some(&format!("barcode = \"{term}\"")))?
+            } else {
+                client.search(&term, None)?
+            };
+            let results: Vec<FoodSearchResult> =
+                docs.iter().map(FoodDocument::as_search_result).collect();
+            serialize_results(results)
+        }
+        None => fallback_food(&term, db_env, db),
+    }
+}
+
+/// Barcodes are all-digit terms; anything else is treated as free text.
+fn is_barcode(term: &str) -> bool {
+    !term.is_empty() && term.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Serializes borrowed results while they still reference their source docs.
+fn serialize_results(results: Vec<FoodSearchResult>) -> anyhow::Result<Response> {
+    let body = serde_json::to_vec(&FoodSearchResponse { results })?;
+    Ok(Response::builder(Status::OK)
+        .with_header(HeaderName::CONTENT_TYPE, "application/json")?
+        .with_body(body))
+}
+
+/// Without Meilisearch we can still serve foods the user has logged before,
+/// stored under the `food.` recall keys written by `post_journal`.
+fn fallback_food(term: &str, db_env: &Env, db: &Database<Str, Str>) -> anyhow::Result<Response> {
+    #[derive(Deserialize)]
+    struct Recall<'a> {
+        text: &'a str,
+        qty: f64,
+        qty_units: &'a str,
+        calories: u64,
+        carbohydrate: u64,
+        fat: u64,
+        protein: u64,
+    }
+
+    /// An owned copy of a matched recall, since `FoodSearchResult` borrows and
+    /// the source `value` doesn't outlive the iterator.
+    struct Matched {
+        text: String,
+        qty: f64,
+        qty_units: String,
+        calories: u64,
+        carbohydrate: u64,
+        fat: u64,
+        protein: u64,
+    }
+
+    let needle = term.to_lowercase();
+    let rtxn = db_env.read_txn()?;
+
+    let mut matched: Vec<Matched> = Vec::new();
+    for record in db.prefix_iter(&rtxn, "food.")? {
+        let (_, value) = record?;
+        let recall = serde_json::from_str::<Recall>(value)?;
+        if !recall.text.to_lowercase().contains(&needle) {
+            continue;
+        }
+        matched.push(Matched {
+            text: recall.text.to_string(),
+            qty: recall.qty,
+            qty_units: recall.qty_units.to_string(),
+            calories: recall.calories,
+            carbohydrate: recall.carbohydrate,
+            fat: recall.fat,
+            protein: recall.protein,
+        });
+    }
+    rtxn.commit()?;
+
+    // Recall macros are the amounts the user actually logged, so echo the
+    // logged quantity rather than the 100 g basis used for OFF documents.
+    let results: Vec<FoodSearchResult> = matched
+        .iter()
+        .map(|m| FoodSearchResult {
+            barcode: "",
+            text: &m.text,
+            brand: "",
+            qty: m.qty,
+            qty_units: &m.qty_units,
+            calories: m.calories,
+            carbohydrate: m.carbohydrate,
+            fat: m.fat,
+            protein: m.protein,
+        })
+        .collect();
+    serialize_results(results)
+}
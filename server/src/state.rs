@@ -1,15 +1,455 @@
+use crate::error::FjError;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use heed::types::Str;
+use heed::{Database, Env, RoTxn, RwTxn};
 use oxhttp::model::{HeaderName, Request};
-use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-struct FjState {
-    // http client
-    // meili client
-    // lmdb handle
+/// Name of the cookie the login endpoint sets and `extract_user_id` reads.
+pub const SESSION_COOKIE: &str = "fj_session";
+
+/// How long an issued session stays valid, in seconds (30 days).
+pub const SESSION_TTL_SECS: u64 = 60 * 60 * 24 * 30;
+
+/// A stored session: which user it authenticates and when it was minted.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SessionRecord {
+    pub user_id: String,
+    /// Unix seconds at creation, used to enforce the TTL.
+    pub created: u64,
 }
 
-pub fn extract_user_id(request: &Request) -> anyhow::Result<&str> {
-    Ok(request
-        .header(&HeaderName::from_str("x-fj-user")?)
-        .unwrap()
-        .to_str()?)
+/// Current time in whole Unix seconds.
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Today's date in UTC as an ISO `yyyy-mm-dd` string, used as the default key
+/// for dated records when a caller doesn't supply one.
+pub fn today_utc() -> anyhow::Result<String> {
+    let date = jiff::Timestamp::from_second(now_secs() as i64)?
+        .to_zoned(jiff::tz::TimeZone::UTC)
+        .date();
+    Ok(date.to_string())
+}
+
+/// Generates an opaque 256-bit session token, hex-encoded.
+pub fn new_session_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Shared application state handed to every request handler. Generic over the
+/// [`Storage`] backend so the handlers never name a concrete database type.
+pub struct FjState<S: Storage> {
+    pub storage: S,
+    /// Upper bound on the size of a request body, from [`crate::config::Config`].
+    max_body_bytes: usize,
+}
+
+impl<S: Storage> FjState<S> {
+    pub fn new(storage: S, max_body_bytes: usize) -> Self {
+        FjState {
+            storage,
+            max_body_bytes,
+        }
+    }
+
+    /// Reads a JSON request body, rejecting a non-JSON `Content-Type` with
+    /// `415` and a body larger than the configured cap with `413` before any
+    /// deserialization is attempted. Mutating handlers call this instead of
+    /// reading `body_mut()` directly so the checks live in one place.
+    pub fn read_json_body(&self, request: &mut Request) -> anyhow::Result<Vec<u8>> {
+        read_json_body(request, self.max_body_bytes)
+    }
+}
+
+/// Validates the `Content-Type` and reads the request body with a hard byte
+/// cap. See [`FjState::read_json_body`].
+pub fn read_json_body(request: &mut Request, max_bytes: usize) -> anyhow::Result<Vec<u8>> {
+    let is_json = request
+        .header(&HeaderName::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim_start().starts_with("application/json"))
+        .unwrap_or(false);
+    if !is_json {
+        return Err(FjError::unsupported_media_type("expected application/json body").into());
+    }
+
+    // Reject early when the advertised length already exceeds the cap.
+    if let Some(len) = request
+        .header(&HeaderName::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok())
+    {
+        if len > max_bytes {
+            return Err(FjError::payload_too_large("request body too large").into());
+        }
+    }
+
+    // Read one byte past the cap so an over-long body is detected even when the
+    // advertised `Content-Length` was absent or lied.
+    let mut buf = Vec::new();
+    request
+        .body_mut()
+        .take(max_bytes as u64 + 1)
+        .read_to_end(&mut buf)?;
+    if buf.len() > max_bytes {
+        return Err(FjError::payload_too_large("request body too large").into());
+    }
+
+    Ok(buf)
+}
+
+/// An inclusive date window used when scanning journal entries. `None` bounds
+/// mean "unbounded on that end"; dates are ISO `yyyy-mm-dd` strings, which sort
+/// lexically, so comparisons are plain string comparisons.
+#[derive(Default)]
+pub struct DateRange {
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+impl DateRange {
+    /// Matches every entry.
+    pub fn all() -> Self {
+        DateRange::default()
+    }
+
+    fn contains(&self, date: &str) -> bool {
+        self.start.as_deref().map_or(true, |s| date >= s)
+            && self.end.as_deref().map_or(true, |e| date <= e)
+    }
+}
+
+/// Domain-level persistence operations. Handlers depend on this trait rather
+/// than on heed directly, so a SQLite/Postgres backend can be dropped in later
+/// without touching handler logic.
+pub trait Storage {
+    type ReadTxn<'a>
+    where
+        Self: 'a;
+    type WriteTxn<'a>
+    where
+        Self: 'a;
+
+    fn read_txn(&self) -> anyhow::Result<Self::ReadTxn<'_>>;
+    fn write_txn(&self) -> anyhow::Result<Self::WriteTxn<'_>>;
+    fn commit(&self, txn: Self::WriteTxn<'_>) -> anyhow::Result<()>;
+
+    /// Returns the raw stored JSON record for a user, if present.
+    fn get_user(&self, txn: &Self::ReadTxn<'_>, user: &str) -> anyhow::Result<Option<String>>;
+    fn put_user(&self, txn: &mut Self::WriteTxn<'_>, user: &str, record: &str)
+        -> anyhow::Result<()>;
+    /// All users as `(user_name, record_json)` pairs.
+    fn iter_users(&self, txn: &Self::ReadTxn<'_>) -> anyhow::Result<Vec<(String, String)>>;
+
+    /// Upserts a journal entry keyed by its stable `id`, which is the
+    /// `{date}.{timestamp}` suffix that [`Storage::iter_journal_entries`]
+    /// emits, so the read and write paths agree on a single id format.
+    fn put_journal_entry(
+        &self,
+        txn: &mut Self::WriteTxn<'_>,
+        user: &str,
+        id: &str,
+        record: &str,
+    ) -> anyhow::Result<()>;
+    /// Stores a food record under the short `food.` recall key so it can be
+    /// re-offered when the search backend is unavailable.
+    fn put_food_recall(
+        &self,
+        txn: &mut Self::WriteTxn<'_>,
+        text: &str,
+        record: &str,
+    ) -> anyhow::Result<()>;
+    /// A single journal entry by its stable id, if present. Used to compute
+    /// totals deltas on upsert.
+    fn get_journal_entry(
+        &self,
+        txn: &Self::ReadTxn<'_>,
+        user: &str,
+        id: &str,
+    ) -> anyhow::Result<Option<String>>;
+    /// Journal entries for a user within `range`, as `(id, record_json)` pairs
+    /// where `id` is the `date.timestamp` suffix of the key.
+    fn iter_journal_entries(
+        &self,
+        txn: &Self::ReadTxn<'_>,
+        user: &str,
+        range: &DateRange,
+    ) -> anyhow::Result<Vec<(String, String)>>;
+
+    /// The aggregated daily-totals record for a user on a date, if any.
+    fn get_total(
+        &self,
+        txn: &Self::ReadTxn<'_>,
+        user: &str,
+        date: &str,
+    ) -> anyhow::Result<Option<String>>;
+    fn put_total(
+        &self,
+        txn: &mut Self::WriteTxn<'_>,
+        user: &str,
+        date: &str,
+        record: &str,
+    ) -> anyhow::Result<()>;
+
+    /// The user's current logical date, as tracked by the end-day clock.
+    fn get_current_date(&self, txn: &Self::ReadTxn<'_>, user: &str) -> anyhow::Result<String>;
+
+    fn put_session(
+        &self,
+        txn: &mut Self::WriteTxn<'_>,
+        token: &str,
+        record: &str,
+    ) -> anyhow::Result<()>;
+    fn get_session(&self, txn: &Self::ReadTxn<'_>, token: &str) -> anyhow::Result<Option<String>>;
+
+    /// Upserts a dated body measurement (one record per `date`).
+    fn put_measurement(
+        &self,
+        txn: &mut Self::WriteTxn<'_>,
+        user: &str,
+        date: &str,
+        record: &str,
+    ) -> anyhow::Result<()>;
+    /// Body measurements for a user within `range`, as `(date, record_json)`
+    /// pairs in ascending date order.
+    fn iter_measurements(
+        &self,
+        txn: &Self::ReadTxn<'_>,
+        user: &str,
+        range: &DateRange,
+    ) -> anyhow::Result<Vec<(String, String)>>;
+}
+
+/// LMDB-backed [`Storage`], wrapping the single `Str -> Str` database used for
+/// all records. Key formats live here so they are defined in one place.
+#[derive(Clone)]
+pub struct LmdbStorage {
+    env: Env,
+    db: Database<Str, Str>,
+}
+
+impl LmdbStorage {
+    pub fn new(env: Env, db: Database<Str, Str>) -> Self {
+        LmdbStorage { env, db }
+    }
+
+    /// The underlying environment, for subsystems not yet migrated onto the
+    /// trait (e.g. the OpenFoodFacts loader).
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// The underlying database handle.
+    pub fn db(&self) -> Database<Str, Str> {
+        self.db
+    }
+}
+
+impl Storage for LmdbStorage {
+    type ReadTxn<'a> = RoTxn<'a>;
+    type WriteTxn<'a> = RwTxn<'a>;
+
+    fn read_txn(&self) -> anyhow::Result<RoTxn<'_>> {
+        Ok(self.env.read_txn()?)
+    }
+
+    fn write_txn(&self) -> anyhow::Result<RwTxn<'_>> {
+        Ok(self.env.write_txn()?)
+    }
+
+    fn commit(&self, txn: RwTxn<'_>) -> anyhow::Result<()> {
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn get_user(&self, txn: &RoTxn<'_>, user: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.db.get(txn, &format!("user.{user}"))?.map(str::to_string))
+    }
+
+    fn put_user(&self, txn: &mut RwTxn<'_>, user: &str, record: &str) -> anyhow::Result<()> {
+        self.db.put(txn, &format!("user.{user}"), record)?;
+        Ok(())
+    }
+
+    fn iter_users(&self, txn: &RoTxn<'_>) -> anyhow::Result<Vec<(String, String)>> {
+        let mut users = Vec::new();
+        for record in self.db.prefix_iter(txn, "user.")? {
+            let (key, value) = record?;
+            users.push((key["user.".len()..].to_string(), value.to_string()));
+        }
+        Ok(users)
+    }
+
+    fn put_journal_entry(
+        &self,
+        txn: &mut RwTxn<'_>,
+        user: &str,
+        id: &str,
+        record: &str,
+    ) -> anyhow::Result<()> {
+        self.db.put(txn, &format!("entry.{user}.{id}"), record)?;
+        Ok(())
+    }
+
+    fn put_food_recall(&self, txn: &mut RwTxn<'_>, text: &str, record: &str) -> anyhow::Result<()> {
+        // Truncate on a char boundary so short or multi-byte `text` can't panic
+        // the handler thread on a bad byte slice.
+        let end = (0..=text.len().min(16))
+            .rev()
+            .find(|&i| text.is_char_boundary(i))
+            .unwrap_or(0);
+        let key = &text[..end];
+        self.db.put(txn, &format!("food.{key}"), record)?;
+        Ok(())
+    }
+
+    fn get_total(&self, txn: &RoTxn<'_>, user: &str, date: &str) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .db
+            .get(txn, &format!("total.{user}.{date}"))?
+            .map(str::to_string))
+    }
+
+    fn put_total(
+        &self,
+        txn: &mut RwTxn<'_>,
+        user: &str,
+        date: &str,
+        record: &str,
+    ) -> anyhow::Result<()> {
+        self.db.put(txn, &format!("total.{user}.{date}"), record)?;
+        Ok(())
+    }
+
+    fn get_journal_entry(
+        &self,
+        txn: &RoTxn<'_>,
+        user: &str,
+        id: &str,
+    ) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .db
+            .get(txn, &format!("entry.{user}.{id}"))?
+            .map(str::to_string))
+    }
+
+    fn iter_journal_entries(
+        &self,
+        txn: &RoTxn<'_>,
+        user: &str,
+        range: &DateRange,
+    ) -> anyhow::Result<Vec<(String, String)>> {
+        let prefix = format!("entry.{user}.");
+        let mut entries = Vec::new();
+        for record in self.db.prefix_iter(txn, &prefix)? {
+            let (key, value) = record?;
+            let id = &key[prefix.len()..];
+            let date = id.split('.').next().unwrap_or(id);
+            if range.contains(date) {
+                entries.push((id.to_string(), value.to_string()));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn get_current_date(&self, txn: &RoTxn<'_>, user: &str) -> anyhow::Result<String> {
+        #[derive(serde::Deserialize)]
+        struct InnerStructure {
+            current_date: String,
+        }
+
+        let record = self
+            .db
+            .get(txn, &format!("user.{user}"))?
+            .ok_or_else(|| anyhow::anyhow!("User does not exist in db"))?;
+        Ok(serde_json::from_str::<InnerStructure>(record)?.current_date)
+    }
+
+    fn put_session(&self, txn: &mut RwTxn<'_>, token: &str, record: &str) -> anyhow::Result<()> {
+        self.db.put(txn, &format!("session.{token}"), record)?;
+        Ok(())
+    }
+
+    fn get_session(&self, txn: &RoTxn<'_>, token: &str) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .db
+            .get(txn, &format!("session.{token}"))?
+            .map(str::to_string))
+    }
+
+    fn put_measurement(
+        &self,
+        txn: &mut RwTxn<'_>,
+        user: &str,
+        date: &str,
+        record: &str,
+    ) -> anyhow::Result<()> {
+        self.db
+            .put(txn, &format!("measurement.{user}.{date}"), record)?;
+        Ok(())
+    }
+
+    fn iter_measurements(
+        &self,
+        txn: &RoTxn<'_>,
+        user: &str,
+        range: &DateRange,
+    ) -> anyhow::Result<Vec<(String, String)>> {
+        let prefix = format!("measurement.{user}.");
+        let mut measurements = Vec::new();
+        for record in self.db.prefix_iter(txn, &prefix)? {
+            let (key, value) = record?;
+            let date = &key[prefix.len()..];
+            if range.contains(date) {
+                measurements.push((date.to_string(), value.to_string()));
+            }
+        }
+        Ok(measurements)
+    }
+}
+
+/// Resolves the caller's session token (from the `fj_session` cookie or an
+/// `Authorization: Bearer` header) to a user id, returning a `401` [`FjError`]
+/// when the token is missing, unknown, or expired.
+pub fn extract_user_id<S: Storage>(request: &Request, storage: &S) -> anyhow::Result<String> {
+    let token =
+        session_token(request).ok_or_else(|| FjError::unauthorized("missing session token"))?;
+
+    let rtxn = storage.read_txn()?;
+    let record = storage.get_session(&rtxn, &token)?;
+    drop(rtxn);
+
+    let record = record.ok_or_else(|| FjError::unauthorized("invalid session token"))?;
+    let session = serde_json::from_str::<SessionRecord>(&record)?;
+
+    if now_secs() > session.created + SESSION_TTL_SECS {
+        return Err(FjError::unauthorized("session expired").into());
+    }
+
+    Ok(session.user_id)
+}
+
+/// Extracts the session token from the request, preferring an
+/// `Authorization: Bearer` header over the session cookie.
+fn session_token(request: &Request) -> Option<String> {
+    if let Some(auth) = request.header(&HeaderName::AUTHORIZATION) {
+        if let Some(token) = auth.to_str().ok().and_then(|v| v.strip_prefix("Bearer ")) {
+            return Some(token.trim().to_string());
+        }
+    }
+
+    let cookie = request.header(&HeaderName::COOKIE)?.to_str().ok()?;
+    cookie.split(';').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        (name.trim() == SESSION_COOKIE).then(|| value.trim().to_string())
+    })
 }